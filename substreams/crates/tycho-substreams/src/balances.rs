@@ -10,15 +10,89 @@
 //!    library method directly for this.
 //! 3. In the output module, use aggregate_balance_changes to receive an aggregated map of absolute
 //!    balances.
-use crate::pb::tycho::evm::v1::{BalanceChange, BlockBalanceDeltas, Transaction};
+use crate::pb::tycho::evm::v1::{BalanceChange, BalanceDelta, BlockBalanceDeltas, Transaction};
 use itertools::Itertools;
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, fmt, str::FromStr};
 use substreams::{
-    key,
     pb::substreams::StoreDeltas,
     prelude::{BigInt, StoreAdd},
 };
 
+/// Separator between the component and token segments of a balance store key.
+pub const BALANCE_KEY_SEPARATOR: u8 = b':';
+
+/// Codec for the stable `component_id:hex(token)` keys used by the additive balance
+/// store.
+///
+/// Centralizes the key format that [`store_balance_changes`] writes and
+/// [`aggregate_balances_changes`] reads back, so the two sides can never drift apart.
+/// [`write_store_key`](BalanceKey::write_store_key) formats a key into a caller-owned
+/// `String` that is cleared and reused across deltas, and
+/// [`decode`](BalanceKey::decode) splits a key back into its segments in place without
+/// copying.
+///
+/// ## Scope — the fully allocation-free path chunk0-4 asked for is not reachable here
+///
+/// chunk0-4 asked to drop the per-delta UTF-8/hex round-trip entirely by pushing a raw
+/// byte key through the store. That is **not achievable** with the substreams store API
+/// as this crate uses it: `StoreAdd::add` takes the key by value as a `String`, and
+/// store keys must be valid UTF-8, so a token's raw bytes have to be hex-encoded to be
+/// a legal key — and read back with `hex::decode`. This codec therefore still hex-codes
+/// the token and still hands `store.add` an owned `String` per delta; it only removes
+/// the intermediate `hex::encode` and component-id copies and keeps the encode/decode
+/// sides in one place. The
+/// net win over the baseline's extra allocations is modest, and the original
+/// allocation-free goal would need a store API that accepts `&[u8]` keys.
+pub struct BalanceKey<'a> {
+    component_id: &'a [u8],
+    token: &'a [u8],
+}
+
+impl<'a> BalanceKey<'a> {
+    pub fn new(component_id: &'a [u8], token: &'a [u8]) -> Self {
+        Self { component_id, token }
+    }
+
+    pub fn component_id(&self) -> &'a [u8] {
+        self.component_id
+    }
+
+    pub fn token(&self) -> &'a [u8] {
+        self.token
+    }
+
+    /// Write the stable on-store key (`component_id:hex(token)`) into `buf`, clearing
+    /// any previous contents so the same buffer can be reused across deltas. The
+    /// component id is assumed to be valid UTF-8, as elsewhere in this module.
+    pub fn write_store_key(&self, buf: &mut String) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        buf.clear();
+        buf.reserve(self.component_id.len() + 1 + self.token.len() * 2);
+        buf.push_str(
+            std::str::from_utf8(self.component_id)
+                .expect("delta.component_id is not valid utf-8!"),
+        );
+        buf.push(BALANCE_KEY_SEPARATOR as char);
+        for &b in self.token {
+            buf.push(HEX[(b >> 4) as usize] as char);
+            buf.push(HEX[(b & 0x0f) as usize] as char);
+        }
+    }
+
+    /// Zero-copy decode of an on-store key: borrow the component and token segments out
+    /// of `buf`, split at the first separator. Note that [`token`](BalanceKey::token)
+    /// then yields the hex-encoded token bytes (as written by
+    /// [`write_store_key`](BalanceKey::write_store_key)), which callers decode with
+    /// `hex::decode`.
+    pub fn decode(buf: &'a [u8]) -> Self {
+        let sep = buf
+            .iter()
+            .position(|&b| b == BALANCE_KEY_SEPARATOR)
+            .expect("balance key missing separator");
+        Self { component_id: &buf[..sep], token: &buf[sep + 1..] }
+    }
+}
+
 /// Store relative balances changes in a additive manner.
 ///
 /// Effectively aggregates the relative balances changes into an absolute balances.
@@ -35,16 +109,13 @@ use substreams::{
 /// [deltas mode](https://substreams.streamingfast.io/documentation/develop/manifest-modules/types#deltas-mode).
 pub fn store_balance_changes(deltas: BlockBalanceDeltas, store: impl StoreAdd<BigInt>) {
     let mut previous_ordinal = HashMap::<String, u64>::new();
+    let mut key_buf = String::new();
     deltas
         .balance_deltas
         .iter()
         .for_each(|delta| {
-            let balance_key = format!(
-                "{0}:{1}",
-                String::from_utf8(delta.component_id.clone())
-                    .expect("delta.component_id is not valid utf-8!"),
-                hex::encode(&delta.token)
-            );
+            BalanceKey::new(&delta.component_id, &delta.token).write_store_key(&mut key_buf);
+            let balance_key = key_buf.clone();
             let current_ord = delta.ord;
             previous_ordinal
                 .entry(balance_key.clone())
@@ -63,6 +134,86 @@ pub fn store_balance_changes(deltas: BlockBalanceDeltas, store: impl StoreAdd<Bi
         });
 }
 
+/// Groups `deltas.balance_deltas` by their `component_id:token` key, stable-sorts
+/// each group by `ord`, and merges deltas that share an identical ordinal by summing
+/// their `BigInt` values into a single entry.
+///
+/// This is the tolerant counterpart to the strict ordinal check performed by
+/// [`store_balance_changes`]: instead of aborting the whole run when an upstream
+/// `map` emits events slightly out of order, the deltas are sanitized into a
+/// canonical, strictly-increasing-per-key ordering. Groups are emitted in
+/// first-seen order and, within each group, in ascending ordinal order.
+///
+/// When this sanitizing path is used the sanitized deltas (not the raw ones) must
+/// also be fed to [`aggregate_balances_changes`] so the store writes and the
+/// relative-delta stream stay in sync.
+pub fn sort_and_merge_balance_deltas(deltas: BlockBalanceDeltas) -> BlockBalanceDeltas {
+    // Group by key while preserving first-seen order.
+    let mut order = Vec::<String>::new();
+    let mut groups = HashMap::<String, Vec<BalanceDelta>>::new();
+    let mut key_buf = String::new();
+    for delta in deltas.balance_deltas {
+        BalanceKey::new(&delta.component_id, &delta.token).write_store_key(&mut key_buf);
+        let balance_key = key_buf.clone();
+        groups
+            .entry(balance_key.clone())
+            .or_insert_with(|| {
+                order.push(balance_key);
+                Vec::new()
+            })
+            .push(delta);
+    }
+
+    let mut balance_deltas = Vec::new();
+    for key in order {
+        let mut group = groups
+            .remove(&key)
+            .expect("group key present in order");
+        group.sort_by_key(|delta| delta.ord);
+
+        // Merge runs of identical ordinals by summing their relative deltas.
+        for (_, run) in &group
+            .into_iter()
+            .group_by(|delta| delta.ord)
+        {
+            let mut run = run.into_iter();
+            let mut merged = run
+                .next()
+                .expect("group_by yields non-empty runs");
+            let mut sum = BigInt::from_signed_bytes_be(&merged.delta);
+            for delta in run {
+                sum = sum + BigInt::from_signed_bytes_be(&delta.delta);
+            }
+            merged.delta = sum.to_signed_bytes_be();
+            balance_deltas.push(merged);
+        }
+    }
+
+    BlockBalanceDeltas { balance_deltas }
+}
+
+/// Ordinal-tolerant variant of [`store_balance_changes`].
+///
+/// Sanitizes `deltas` via [`sort_and_merge_balance_deltas`] before writing, so an
+/// upstream `map` that emits a single token's deltas slightly out of order no longer
+/// aborts the whole substreams run. Because the deltas are sorted and same-ordinal
+/// writes are merged up front, the store only ever sees strictly increasing ordinals
+/// per key.
+///
+/// Callers using this path must feed the sanitized deltas to
+/// [`aggregate_balances_changes`] as well; see [`sort_and_merge_balance_deltas`].
+pub fn store_balance_changes_sorted(deltas: BlockBalanceDeltas, store: impl StoreAdd<BigInt>) {
+    let merged = sort_and_merge_balance_deltas(deltas);
+    let mut key_buf = String::new();
+    merged
+        .balance_deltas
+        .iter()
+        .for_each(|delta| {
+            BalanceKey::new(&delta.component_id, &delta.token).write_store_key(&mut key_buf);
+            store.add(delta.ord, key_buf.clone(), BigInt::from_signed_bytes_be(&delta.delta));
+        });
+}
+
 type TxAggregatedBalances = HashMap<Vec<u8>, (Transaction, HashMap<Vec<u8>, BalanceChange>)>;
 
 /// Aggregates absolute balances per transaction and token.
@@ -72,8 +223,25 @@ type TxAggregatedBalances = HashMap<Vec<u8>, (Transaction, HashMap<Vec<u8>, Bala
 /// * `deltas` - A `BlockBalanceDeltas` message containing the relative balances changes.
 ///
 /// Reads absolute balance values from the additive store (see `store_balance_changes`
-/// on how to create such a store), proceeds to zip them with the relative balance
-/// deltas to associate balance values to token and component.
+/// on how to create such a store) and joins them with the relative balance deltas
+/// to associate balance values to token and component.
+///
+/// Rather than pairing the two streams positionally, the relative deltas are indexed
+/// by `(component_id, token, ord)` and each `StoreDelta` is matched against that index
+/// using the same key reconstructed via [`BalanceKey::decode`] plus `store_delta.ordinal`.
+/// This keeps the aggregation correct even when the store emits its writes in a
+/// different order than the relative-delta stream; a different count is detected
+/// rather than tolerated. Every inconsistency is surfaced as a panic rather than silently attributing a balance to
+/// the wrong token/component: a relative delta whose `(component_id, token, ord)` key
+/// appears more than once, a `StoreDelta` with no matching relative delta, and — after
+/// all store deltas are consumed — any relative delta that was never matched by a store
+/// write.
+///
+/// Because the index is keyed by ordinal, a store that coalesces two same-ordinal
+/// writes for one key must be fed the same coalesced relative deltas (e.g. via
+/// [`sort_and_merge_balance_deltas`]); otherwise the surviving duplicate relative delta
+/// trips the duplicate-key panic. Feeding matching sanitized streams to both
+/// [`store_balance_changes_sorted`] and this function keeps them in sync.
 ///
 /// Will keep the last balance change per token per transaction if there are multiple
 /// changes.
@@ -84,30 +252,88 @@ pub fn aggregate_balances_changes(
     balance_store: StoreDeltas,
     deltas: BlockBalanceDeltas,
 ) -> TxAggregatedBalances {
-    balance_store
+    // Index the relative deltas by their reconstructable store key so store deltas can
+    // be matched by content instead of position.
+    let mut relative_by_key = HashMap::<(Vec<u8>, Vec<u8>, u64), Transaction>::new();
+    for balance_delta in deltas.balance_deltas {
+        let rel_key = (
+            balance_delta.component_id.clone(),
+            balance_delta.token.clone(),
+            balance_delta.ord,
+        );
+        let tx = balance_delta
+            .tx
+            .expect("Missing transaction on delta");
+        if relative_by_key
+            .insert(rel_key, tx)
+            .is_some()
+        {
+            panic!(
+                "Duplicate relative balance delta for {}:{} at ordinal {}",
+                String::from_utf8(balance_delta.component_id.clone())
+                    .expect("delta.component_id is not valid utf-8!"),
+                hex::encode(&balance_delta.token),
+                balance_delta.ord,
+            );
+        }
+    }
+
+    // Resolve each store delta against the relative-delta index, consuming matches so
+    // that whatever is left over afterwards is an unmatched relative delta.
+    let changes: Vec<(Transaction, BalanceChange)> = balance_store
         .deltas
         .into_iter()
-        .zip(deltas.balance_deltas)
-        .map(|(store_delta, balance_delta)| {
-            let component_id = key::segment_at(&store_delta.key, 0);
-            let token_id = key::segment_at(&store_delta.key, 1);
+        .map(|store_delta| {
+            let parsed = BalanceKey::decode(store_delta.key.as_bytes());
+            let component_id = parsed.component_id().to_vec();
+            let token = hex::decode(parsed.token()).expect("Token ID not valid hex");
             // store_delta.new_value is an ASCII string representing an integer
             let ascii_string =
                 String::from_utf8(store_delta.new_value.clone()).expect("Invalid UTF-8 sequence");
             let balance = BigInt::from_str(&ascii_string).expect("Failed to parse integer");
             let big_endian_bytes_balance = balance.to_bytes_be().1;
 
+            let lookup_key = (component_id.clone(), token.clone(), store_delta.ordinal);
+            let tx = relative_by_key
+                .remove(&lookup_key)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "No relative balance delta matching store change {} at ordinal {}",
+                        store_delta.key, store_delta.ordinal
+                    )
+                });
+
             (
-                balance_delta
-                    .tx
-                    .expect("Missing transaction on delta"),
+                tx,
                 BalanceChange {
-                    token: hex::decode(token_id).expect("Token ID not valid hex"),
+                    token,
                     balance: big_endian_bytes_balance,
-                    component_id: component_id.as_bytes().to_vec(),
+                    component_id,
                 },
             )
         })
+        .collect();
+
+    // Symmetric check: any relative delta not consumed above has no matching store
+    // write, another 1:1 misalignment that must not be swallowed silently.
+    if !relative_by_key.is_empty() {
+        let leftovers = relative_by_key
+            .keys()
+            .map(|(component_id, token, ord)| {
+                format!(
+                    "{}:{} at ordinal {}",
+                    String::from_utf8(component_id.clone())
+                        .expect("delta.component_id is not valid utf-8!"),
+                    hex::encode(token),
+                    ord,
+                )
+            })
+            .join(", ");
+        panic!("Relative balance delta(s) with no matching store change: {}", leftovers);
+    }
+
+    changes
+        .into_iter()
         // We need to group the balance changes by tx hash for the `TransactionContractChanges` agg
         .group_by(|(tx, _)| tx.hash.clone())
         .into_iter()
@@ -123,6 +349,323 @@ pub fn aggregate_balances_changes(
         .collect()
 }
 
+/// Discriminates the kind of balance carried by an [`ExtendedBalanceChange`].
+///
+/// This is the seed of a richer balance schema; today every aggregated change is an
+/// [`Absolute`](BalanceChangeKind::Absolute) reserve, but new variants can be added
+/// without touching the legacy wire `BalanceChange`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalanceChangeKind {
+    /// The balance is the component's full absolute reserve for the token.
+    #[default]
+    Absolute,
+}
+
+/// A balance change carrying extra metadata on top of the wire [`BalanceChange`].
+///
+/// Lets the SDK introduce richer balance information (here, a [`BalanceChangeKind`]
+/// discriminator) ahead of a protobuf schema bump. [`into_legacy`] drops the extra
+/// metadata to recover the exact current wire shape for consumers that are still
+/// pinned to it.
+///
+/// [`into_legacy`]: ExtendedBalanceChange::into_legacy
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedBalanceChange {
+    pub inner: BalanceChange,
+    pub kind: BalanceChangeKind,
+}
+
+impl ExtendedBalanceChange {
+    /// Downgrade to the legacy wire `BalanceChange`, discarding the extra metadata.
+    pub fn into_legacy(self) -> BalanceChange {
+        self.inner
+    }
+}
+
+impl From<BalanceChange> for ExtendedBalanceChange {
+    fn from(inner: BalanceChange) -> Self {
+        Self { inner, kind: BalanceChangeKind::default() }
+    }
+}
+
+/// Selects the balance-change output shape emitted by
+/// [`aggregate_balances_changes_versioned`].
+///
+/// Defaults to [`Legacy`](BalanceChangeVersion::Legacy) so downstream substreams
+/// packages keep receiving the current wire format until they explicitly opt in to
+/// [`Extended`](BalanceChangeVersion::Extended).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalanceChangeVersion {
+    #[default]
+    Legacy,
+    Extended,
+}
+
+/// Output of [`aggregate_balances_changes_versioned`], one variant per
+/// [`BalanceChangeVersion`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionedTxBalances {
+    Legacy(TxAggregatedBalances),
+    Extended(HashMap<Vec<u8>, (Transaction, HashMap<Vec<u8>, ExtendedBalanceChange>)>),
+}
+
+impl VersionedTxBalances {
+    /// Collapse either variant down to the legacy aggregation, so callers that only
+    /// understand the current wire format can consume the extended output too.
+    pub fn into_legacy(self) -> TxAggregatedBalances {
+        match self {
+            VersionedTxBalances::Legacy(balances) => balances,
+            VersionedTxBalances::Extended(balances) => balances
+                .into_iter()
+                .map(|(txh, (tx, changes))| {
+                    let legacy = changes
+                        .into_iter()
+                        .map(|(token, change)| (token, change.into_legacy()))
+                        .collect();
+                    (txh, (tx, legacy))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Versioned wrapper around [`aggregate_balances_changes`].
+///
+/// Runs the same aggregation and then encodes the result at the requested
+/// [`BalanceChangeVersion`]: `Legacy` returns the untouched wire `BalanceChange`
+/// map, `Extended` wraps each change into an [`ExtendedBalanceChange`]. Passing
+/// `BalanceChangeVersion::default()` keeps the legacy behaviour, so opting into the
+/// extended schema is always explicit.
+pub fn aggregate_balances_changes_versioned(
+    balance_store: StoreDeltas,
+    deltas: BlockBalanceDeltas,
+    version: BalanceChangeVersion,
+) -> VersionedTxBalances {
+    let aggregated = aggregate_balances_changes(balance_store, deltas);
+    match version {
+        BalanceChangeVersion::Legacy => VersionedTxBalances::Legacy(aggregated),
+        BalanceChangeVersion::Extended => VersionedTxBalances::Extended(
+            aggregated
+                .into_iter()
+                .map(|(txh, (tx, changes))| {
+                    let extended = changes
+                        .into_iter()
+                        .map(|(token, change)| (token, ExtendedBalanceChange::from(change)))
+                        .collect();
+                    (txh, (tx, extended))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// A single store transition whose resulting absolute balance is negative.
+///
+/// On-chain token reserves can never be negative, so a transition into a
+/// negative absolute balance always points to a miscomputed relative delta in
+/// the upstream `map` module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeBalance {
+    /// The offending `component_id:token` store key.
+    pub key: String,
+    /// The store ordinal at which the negative balance was written.
+    pub ordinal: u64,
+    /// The absolute balance before the write, as stored (ASCII integer).
+    pub old_value: String,
+    /// The absolute balance after the write, as stored (ASCII integer).
+    pub new_value: String,
+}
+
+/// Error returned by [`try_aggregate_balances_changes`] when one or more store
+/// transitions violate the non-negative reserve invariant.
+///
+/// Carries every offending transition so the author can pinpoint all the
+/// relative deltas that broke the accounting in a single pass rather than
+/// fixing them one panic at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeBalanceError {
+    pub offenders: Vec<NegativeBalance>,
+}
+
+impl fmt::Display for NegativeBalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} balance transition(s) dropped below zero:", self.offenders.len())?;
+        for o in &self.offenders {
+            write!(
+                f,
+                "\n  {} @ ord {}: {} -> {}",
+                o.key, o.ordinal, o.old_value, o.new_value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NegativeBalanceError {}
+
+/// Validating variant of [`aggregate_balances_changes`].
+///
+/// Behaves exactly like `aggregate_balances_changes` but first checks the
+/// additive store for the solvency invariant: every absolute balance must stay
+/// non-negative. Any transition whose `new_value` parses to a negative integer
+/// is collected into a [`NegativeBalanceError`] enumerating the offending
+/// `component_id:token` key, the store `ordinal` and both `old_value` and
+/// `new_value`, so a corrupted delta stream is surfaced here instead of being
+/// shipped downstream.
+///
+/// Callers who want to opt out of the check can keep using the unchecked
+/// `aggregate_balances_changes`.
+pub fn try_aggregate_balances_changes(
+    balance_store: StoreDeltas,
+    deltas: BlockBalanceDeltas,
+) -> Result<TxAggregatedBalances, NegativeBalanceError> {
+    let offenders: Vec<NegativeBalance> = balance_store
+        .deltas
+        .iter()
+        .filter_map(|store_delta| {
+            let new_value = String::from_utf8(store_delta.new_value.clone())
+                .expect("Invalid UTF-8 sequence");
+            let balance = BigInt::from_str(&new_value).expect("Failed to parse integer");
+            if balance < BigInt::from(0) {
+                Some(NegativeBalance {
+                    key: store_delta.key.clone(),
+                    ordinal: store_delta.ordinal,
+                    old_value: String::from_utf8(store_delta.old_value.clone())
+                        .expect("Invalid UTF-8 sequence"),
+                    new_value,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    if offenders.is_empty() {
+        Ok(aggregate_balances_changes(balance_store, deltas))
+    } else {
+        Err(NegativeBalanceError { offenders })
+    }
+}
+
+/// The absolute balances for a single component that changed in a block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentBalances {
+    pub component_id: Vec<u8>,
+    pub balances: Vec<BalanceChange>,
+}
+
+/// The components whose absolute balances changed in a single block.
+///
+/// Unlike the per-transaction output of [`aggregate_balances_changes`], this carries
+/// the current absolute balance of each changed `component_id:token` key, grouped by
+/// component and with repeated writes collapsed to their final value — a compact,
+/// tx-free view an indexer can fold directly into its state.
+///
+/// ## Scope — not the full checkpoint chunk0-6 asked for
+///
+/// chunk0-6 asked for a *complete* absolute-balance checkpoint an indexer could
+/// cold-start from without replaying the relative-delta chain. That goal is **not met
+/// here**, and the type is named accordingly. A full checkpoint needs to enumerate
+/// every `component_id:token` the store has ever seen, but substreams store handles
+/// (`StoreGet`/`StoreDelta`) expose no key iteration, and in
+/// [deltas mode](https://substreams.streamingfast.io/documentation/develop/manifest-modules/types#deltas-mode)
+/// the store only surfaces the keys written in the current block. So this carries only
+/// the keys that moved this block; a cold-starting consumer must still fold every
+/// block's changes. Closing the gap would require a different substreams primitive
+/// (an enumerable full-mode store) than this crate exposes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedBlockBalances {
+    pub balances: Vec<ComponentBalances>,
+}
+
+/// Materializes the block's [`ChangedBlockBalances`] from the additive balance store
+/// deltas.
+///
+/// Reads the current absolute balance (the store `new_value`, an ASCII integer) for
+/// every `component_id:token` key written in `balance_store` and emits it as
+/// big-endian magnitude bytes, grouped by component in first-seen order. The store is
+/// consumed in [deltas mode](https://substreams.streamingfast.io/documentation/develop/manifest-modules/types#deltas-mode),
+/// the same way [`aggregate_balances_changes`] reads it, so the output covers exactly
+/// the keys that changed this block — see [`ChangedBlockBalances`] on why this is a
+/// per-block view rather than the complete checkpoint chunk0-6 requested.
+pub fn changed_absolute_balances(balance_store: StoreDeltas) -> ChangedBlockBalances {
+    // Per component, keep token order of first appearance plus the latest balance per
+    // token so repeated writes collapse to the current absolute value.
+    let mut component_order = Vec::<Vec<u8>>::new();
+    let mut by_component =
+        HashMap::<Vec<u8>, (Vec<Vec<u8>>, HashMap<Vec<u8>, BalanceChange>)>::new();
+
+    for store_delta in balance_store.deltas {
+        let parsed = BalanceKey::decode(store_delta.key.as_bytes());
+        let component_id = parsed.component_id().to_vec();
+        let token = hex::decode(parsed.token()).expect("Token ID not valid hex");
+        // store_delta.new_value is an ASCII string representing an integer
+        let ascii_string =
+            String::from_utf8(store_delta.new_value.clone()).expect("Invalid UTF-8 sequence");
+        let balance = BigInt::from_str(&ascii_string).expect("Failed to parse integer");
+
+        let entry = by_component
+            .entry(component_id.clone())
+            .or_insert_with(|| {
+                component_order.push(component_id.clone());
+                (Vec::new(), HashMap::new())
+            });
+        if !entry.1.contains_key(&token) {
+            entry.0.push(token.clone());
+        }
+        entry.1.insert(
+            token.clone(),
+            BalanceChange { token, balance: balance.to_bytes_be().1, component_id: component_id.clone() },
+        );
+    }
+
+    ChangedBlockBalances {
+        balances: component_order
+            .into_iter()
+            .map(|component_id| {
+                let (token_order, mut changes) = by_component
+                    .remove(&component_id)
+                    .expect("component present in order");
+                let balances = token_order
+                    .into_iter()
+                    .map(|token| {
+                        changes
+                            .remove(&token)
+                            .expect("token present in order")
+                    })
+                    .collect();
+                ComponentBalances { component_id, balances }
+            })
+            .collect(),
+    }
+}
+
+/// Emit policy helper for emitting the changed-balances view on an interval.
+///
+/// Returns `true` when a [`ChangedBlockBalances`] view should be emitted at
+/// `block_number` given an "every `interval` blocks" cadence. An `interval` of `0`
+/// disables emission.
+pub fn should_emit_snapshot(block_number: u64, interval: u64) -> bool {
+    interval != 0 && block_number % interval == 0
+}
+
+/// Convenience wrapper that only builds the changed-balances view on emit blocks.
+///
+/// Returns `Some(ChangedBlockBalances)` when [`should_emit_snapshot`] holds for
+/// `block_number`/`interval`, otherwise `None` so the caller can emit plain
+/// incremental deltas instead.
+pub fn changed_absolute_balances_every_n(
+    block_number: u64,
+    interval: u64,
+    balance_store: StoreDeltas,
+) -> Option<ChangedBlockBalances> {
+    if should_emit_snapshot(block_number, interval) {
+        Some(changed_absolute_balances(balance_store))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -300,6 +843,74 @@ mod tests {
         assert_eq!(res_1, Some(BigInt::from_str("+150").unwrap()));
     }
 
+    #[test]
+    fn test_balance_key_roundtrip() {
+        let component_id = b"0x42c0ffee";
+        let token = hex::decode("bad999").unwrap();
+
+        // The store-key format stays the stable `component_id:hex(token)` string.
+        let mut store_key = String::new();
+        BalanceKey::new(component_id, &token).write_store_key(&mut store_key);
+        assert_eq!(store_key, "0x42c0ffee:bad999");
+
+        // Decoding splits the key back into its segments; the token segment stays
+        // hex-encoded for the caller to `hex::decode`.
+        let decoded = BalanceKey::decode(store_key.as_bytes());
+        assert_eq!(decoded.component_id(), component_id);
+        assert_eq!(decoded.token(), b"bad999");
+    }
+
+    #[test]
+    fn test_store_balance_changes_sorted() {
+        let comp_id = "0x42c0ffee"
+            .to_string()
+            .as_bytes()
+            .to_vec();
+        let token_0 = hex::decode("bad999").unwrap();
+        // Out-of-order ordinals with a same-ordinal pair that must be merged.
+        let deltas = BlockBalanceDeltas {
+            balance_deltas: vec![
+                BalanceDelta {
+                    ord: 5,
+                    tx: None,
+                    token: token_0.clone(),
+                    delta: BigInt::from_str("-1")
+                        .unwrap()
+                        .to_signed_bytes_be(),
+                    component_id: comp_id.clone(),
+                },
+                BalanceDelta {
+                    ord: 1,
+                    tx: None,
+                    token: token_0.clone(),
+                    delta: BigInt::from_str("+1000")
+                        .unwrap()
+                        .to_signed_bytes_be(),
+                    component_id: comp_id.clone(),
+                },
+                BalanceDelta {
+                    ord: 1,
+                    tx: None,
+                    token: token_0.clone(),
+                    delta: BigInt::from_str("+5")
+                        .unwrap()
+                        .to_signed_bytes_be(),
+                    component_id: comp_id.clone(),
+                },
+            ],
+        };
+        let store = <MockStore as StoreNew>::new();
+
+        store_balance_changes_sorted(deltas, store.clone());
+        let res = store.get_last(format!(
+            "{}:{}",
+            String::from_utf8(comp_id.clone()).unwrap(),
+            hex::encode(token_0)
+        ));
+        // (1000 + 5) - 1 = 1004
+        assert_eq!(res, Some(BigInt::from_str("+1004").unwrap()));
+    }
+
     #[test]
     fn test_aggregate_balances_changes() {
         let store_deltas = store_deltas();
@@ -345,4 +956,88 @@ mod tests {
         let res = aggregate_balances_changes(store_deltas, balance_deltas);
         assert_eq!(res, exp);
     }
+
+    #[test]
+    fn test_aggregate_balances_changes_versioned() {
+        let legacy = aggregate_balances_changes(store_deltas(), block_balance_deltas());
+
+        // The extended output downgrades back to exactly the legacy aggregation.
+        let extended = aggregate_balances_changes_versioned(
+            store_deltas(),
+            block_balance_deltas(),
+            BalanceChangeVersion::Extended,
+        );
+        assert!(matches!(extended, VersionedTxBalances::Extended(_)));
+        assert_eq!(extended.into_legacy(), legacy);
+
+        // Default version keeps the legacy shape.
+        let default = aggregate_balances_changes_versioned(
+            store_deltas(),
+            block_balance_deltas(),
+            BalanceChangeVersion::default(),
+        );
+        assert_eq!(default, VersionedTxBalances::Legacy(legacy));
+    }
+
+    #[test]
+    fn test_changed_absolute_balances() {
+        let comp_id = "0x42c0ffee"
+            .to_string()
+            .as_bytes()
+            .to_vec();
+        let token_0 = hex::decode("bad999").unwrap();
+        let token_1 = hex::decode("babe00").unwrap();
+
+        let snapshot = changed_absolute_balances(store_deltas());
+
+        // Single component, both tokens at their final absolute balances.
+        assert_eq!(snapshot.balances.len(), 1);
+        let component = &snapshot.balances[0];
+        assert_eq!(component.component_id, comp_id);
+        // One entry per token, collapsed to the current balance.
+        assert_eq!(component.balances.len(), 2);
+
+        let by_token: HashMap<_, _> = component
+            .balances
+            .iter()
+            .map(|bc| (bc.token.clone(), bc.balance.clone()))
+            .collect();
+        assert_eq!(by_token[&token_0], BigInt::from(999).to_bytes_be().1);
+        assert_eq!(by_token[&token_1], BigInt::from(150).to_bytes_be().1);
+    }
+
+    #[test]
+    fn test_should_emit_snapshot() {
+        assert!(should_emit_snapshot(100, 50));
+        assert!(!should_emit_snapshot(101, 50));
+        assert!(!should_emit_snapshot(100, 0));
+        assert!(changed_absolute_balances_every_n(101, 50, store_deltas()).is_none());
+        assert!(changed_absolute_balances_every_n(100, 50, store_deltas()).is_some());
+    }
+
+    #[test]
+    fn test_try_aggregate_balances_changes_ok() {
+        let store_deltas = store_deltas();
+        let balance_deltas = block_balance_deltas();
+
+        let res = try_aggregate_balances_changes(store_deltas.clone(), balance_deltas.clone());
+        assert_eq!(res, Ok(aggregate_balances_changes(store_deltas, balance_deltas)));
+    }
+
+    #[test]
+    fn test_try_aggregate_balances_changes_negative() {
+        let mut store_deltas = store_deltas();
+        // Make the last transition for token_0 drop below zero.
+        let last = store_deltas.deltas.last_mut().unwrap();
+        last.new_value = BigInt::from(-1)
+            .to_string()
+            .as_bytes()
+            .to_vec();
+        let balance_deltas = block_balance_deltas();
+
+        let err = try_aggregate_balances_changes(store_deltas, balance_deltas).unwrap_err();
+        assert_eq!(err.offenders.len(), 1);
+        assert_eq!(err.offenders[0].ordinal, 10);
+        assert_eq!(err.offenders[0].new_value, "-1");
+    }
 }